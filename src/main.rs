@@ -3,13 +3,16 @@ mod services;
 mod utils;
 
 use futures::future::AbortHandle;
+use models::asset::ArtworkVariant;
 use services::{
     blockfrost::BlockFrostService,
     bookio::{BookioService, URL},
+    download::GatewayConfig,
 };
 use std::{path::PathBuf, process};
 use structopt::StructOpt;
 use tokio::signal;
+use tokio::sync::mpsc;
 
 #[derive(StructOpt, Debug)]
 #[structopt(name = "cardano_book_image_fetcher")]
@@ -19,6 +22,31 @@ struct Opt {
 
     #[structopt(short, long, parse(from_os_str))]
     output_dir: PathBuf,
+
+    /// Which artwork variant(s) to download for each asset.
+    #[structopt(long, default_value = "cover", possible_values = &["cover", "thumbnail", "both"])]
+    variant: ArtworkVariant,
+
+    /// Number of metadata-fetch workers pulling asset ids off the shared queue.
+    #[structopt(long, default_value = "4")]
+    metadata_workers: usize,
+
+    /// Number of downloads allowed in flight at once.
+    #[structopt(long, default_value = "3")]
+    download_permits: usize,
+
+    /// Caps BlockFrost metadata requests per second across all workers. Unlimited if unset.
+    #[structopt(long)]
+    rate_limit: Option<u32>,
+
+    /// IPFS gateways to try, in order, for each download; falls back to the
+    /// next one on failure instead of depending on a single gateway.
+    #[structopt(
+        long,
+        use_delimiter = true,
+        default_value = "https://ipfs.io/ipfs/,https://dweb.link/ipfs/,https://cloudflare-ipfs.com/ipfs/"
+    )]
+    gateways: Vec<String>,
 }
 
 #[tokio::main]
@@ -41,14 +69,35 @@ async fn main() {
     }
 
     if let Ok(true) = result {
-        let service = BlockFrostService::new().unwrap();
+        let gateway_config = GatewayConfig { gateways: opt.gateways.clone(), ..GatewayConfig::default() };
+        let service = BlockFrostService::new()
+            .unwrap()
+            .with_gateway_config(gateway_config)
+            .with_concurrency(opt.metadata_workers, opt.download_permits)
+            .with_rate_limit(opt.rate_limit);
 
         // Create an AbortHandle using futures::future::Abortable
         // This allows us to cancel a future from a different context
         let (abort_handle, abort_registration) = AbortHandle::new_pair();
 
+        // Surface aggregate fetch progress as it streams in; a caller with a
+        // progress bar would render `progress_rx`'s updates instead of printing.
+        let (progress_tx, mut progress_rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Some(progress) = progress_rx.recv().await {
+                println!(
+                    "fetch progress: {}/{} ({} failed)",
+                    progress.completed + progress.failed,
+                    progress.total,
+                    progress.failed
+                );
+            }
+        });
+
         let fetch_handle = tokio::spawn(async move {
-            let result = service.fetch_assets_metadata(&opt.policy_id, &opt.output_dir).await;
+            let result = service
+                .fetch_assets_metadata(&opt.policy_id, &opt.output_dir, opt.variant, Some(progress_tx))
+                .await;
             if let Err(e) = result {
                 println!("cannot fetch metadata: {}", e);
                 process::exit(1);
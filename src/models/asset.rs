@@ -1,8 +1,44 @@
 use serde::Deserialize;
+use std::str::FromStr;
 
 /// Defines the structure of the response coming from the BlockFrost API when getting assets.
 #[derive(Deserialize, Debug)]
 pub struct Asset {
     pub asset: String,
     pub src: String,
+    pub media_type: String,
+}
+
+/// Which book.io artwork variant(s) to download for each asset: its
+/// high-resolution cover, its low-res thumbnail, or both.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArtworkVariant {
+    Cover,
+    Thumbnail,
+    Both,
+}
+
+impl FromStr for ArtworkVariant {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "cover" => Ok(ArtworkVariant::Cover),
+            "thumbnail" => Ok(ArtworkVariant::Thumbnail),
+            "both" => Ok(ArtworkVariant::Both),
+            other => Err(format!("unknown artwork variant: {}", other)),
+        }
+    }
+}
+
+impl ArtworkVariant {
+    /// Whether a `files` entry classified as `kind` ("cover" or "thumbnail")
+    /// should be downloaded under this variant selection.
+    pub fn matches(&self, kind: &str) -> bool {
+        match self {
+            ArtworkVariant::Both => true,
+            ArtworkVariant::Cover => kind == "cover",
+            ArtworkVariant::Thumbnail => kind == "thumbnail",
+        }
+    }
 }
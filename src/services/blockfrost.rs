@@ -1,25 +1,51 @@
 //! This module defines a service for interacting with BlockFrost API. It provides methods to fetch assets and download images from given policy id.
 
-use crate::{models::asset::Asset, utils::util::ipfs_to_http};
+use crate::{
+    models::asset::{Asset, ArtworkVariant},
+    utils::util::{extension_for_media_type, ipfs_to_cid, ipfs_to_http, IPFS_GATEWAY},
+};
 
-use super::download::DownloadService;
+use super::download::{DownloadService, GatewayConfig};
 use anyhow::{Context, Result};
 use blockfrost::{load, AssetDetails, BlockFrostApi, BlockFrostSettings};
 use futures::future;
-use reqwest::Url;
 use serde_json::Value;
-use std::{path::PathBuf, sync::Arc};
-use tokio::sync::mpsc;
-use tokio::sync::Semaphore;
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tokio::sync::{mpsc::UnboundedSender, Mutex, Semaphore};
+
+/// Default number of workers concurrently pulling asset ids off the shared
+/// metadata-fetch queue.
+const DEFAULT_METADATA_WORKERS: usize = 4;
+/// Default number of downloads allowed in flight at once.
+const DEFAULT_DOWNLOAD_PERMITS: usize = 3;
+
+/// Aggregate progress of a `fetch_assets_metadata` run, so a caller can
+/// drive a progress bar instead of only seeing per-asset log lines.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Progress {
+    pub completed: usize,
+    pub failed: usize,
+    pub total: usize,
+}
 
 /// BlockFrostService: struct to encapsulate methods related
 /// to interacting with BlockFrost API, an API to fetch Cardano data.
 pub struct BlockFrostService {
     client: BlockFrostApi,
+    gateway_config: GatewayConfig,
+    metadata_workers: usize,
+    download_permits: usize,
+    rate_limit: Option<u32>,
 }
 
-const NUM_CONCURRENT_FETCHES: usize = 1;
-
 impl BlockFrostService {
     /// Constructs a new BlockFrostService.
     ///
@@ -32,7 +58,35 @@ impl BlockFrostService {
         // Limit quantity of items per page listed
         settings.query_parameters.set_count(20);
         let client = BlockFrostApi::new(project_id, settings);
-        Ok(BlockFrostService { client })
+        Ok(BlockFrostService {
+            client,
+            gateway_config: GatewayConfig::default(),
+            metadata_workers: DEFAULT_METADATA_WORKERS,
+            download_permits: DEFAULT_DOWNLOAD_PERMITS,
+            rate_limit: None,
+        })
+    }
+
+    /// Configures the IPFS gateway list and retry/backoff budget used when
+    /// downloading asset images.
+    pub fn with_gateway_config(mut self, gateway_config: GatewayConfig) -> Self {
+        self.gateway_config = gateway_config;
+        self
+    }
+
+    /// Sets how many metadata-fetch workers pull asset ids from the shared
+    /// queue concurrently, and how many downloads may be in flight at once.
+    pub fn with_concurrency(mut self, metadata_workers: usize, download_permits: usize) -> Self {
+        self.metadata_workers = metadata_workers.max(1);
+        self.download_permits = download_permits.max(1);
+        self
+    }
+
+    /// Caps how many BlockFrost metadata requests are issued per second,
+    /// across all metadata workers combined. `None` means unlimited.
+    pub fn with_rate_limit(mut self, requests_per_second: Option<u32>) -> Self {
+        self.rate_limit = requests_per_second;
+        self
     }
 
     /// Fetches all assets pertaining to a policy_id from BlockFrost.
@@ -47,115 +101,118 @@ impl BlockFrostService {
 
     /// Fetches metadata of all assets pertaining to a policy_id.
     ///
-    /// Downloads associated image pertaining to the asset metadata.
+    /// Downloads associated image pertaining to the asset metadata, limited
+    /// to whichever artwork `variant` (cover, thumbnail, or both) was
+    /// requested. A bounded pool of `metadata_workers` pulls asset ids from
+    /// a shared queue, feeding valid image sources into a `download_permits`-
+    /// wide download semaphore, with a global `rate_limit` (if set)
+    /// throttling how fast metadata requests go out. If `progress_tx` is
+    /// given, a [`Progress`] snapshot is sent on it every time an asset
+    /// finishes (successfully or not), so a caller can drive a progress bar.
     ///
     /// Returns a vector of Assets i.e., metadata fetched and a url to the downloaded image.
     pub async fn fetch_assets_metadata(
         &self,
         policy_id: &str,
         output_dir: &PathBuf,
+        variant: ArtworkVariant,
+        progress_tx: Option<UnboundedSender<Progress>>,
     ) -> Result<Vec<Asset>> {
         // fetch all assets related to given policy
-        let mut assets = self.fetch_assets(policy_id).await.context("Failed to fetch assets")?;
-        assets.reverse();
+        let assets = self.fetch_assets(policy_id).await.context("Failed to fetch assets")?;
+        let total = assets.len();
 
-        let (tx, mut rx) =
-            mpsc::channel::<Result<AssetDetails, blockfrost::Error>>(NUM_CONCURRENT_FETCHES);
-        let mut asset_metadata = vec![];
-        let mut download_tasks = Vec::new();
-        let initial_assets = assets.split_off(assets.len().saturating_sub(NUM_CONCURRENT_FETCHES));
-        let mut remaining_tasks = initial_assets.len();
+        // Shared queue of asset ids that every metadata worker pulls from.
+        let queue = Arc::new(Mutex::new(assets.into_iter()));
 
-        // create a semaphore to limit the number of concurrent downloads (limit the cpu usage)
-        let semaphore = Arc::new(Semaphore::new(3));
+        // Shared semaphore bounding how many downloads run at once, regardless
+        // of how many metadata workers are in flight.
+        let download_semaphore = Arc::new(Semaphore::new(self.download_permits));
+        let download_service = Arc::new(
+            DownloadService::new(output_dir.clone())
+                .with_verify(true)
+                .with_gateway_config(self.gateway_config.clone()),
+        );
 
-        let download_service = Arc::new(DownloadService::new(output_dir.clone()));
+        let limiter = self.rate_limit.map(RateLimiter::new).map(Arc::new);
 
+        let completed = Arc::new(AtomicUsize::new(0));
+        let failed = Arc::new(AtomicUsize::new(0));
         let client = Arc::new(self.client.clone());
-        for asset in initial_assets {
-            // create a new task for each asset in order fetch the asset details
-            // concurrently and thereby improving the throughput of the system
-            let tx = tx.clone();
+
+        let num_workers = self.metadata_workers.min(total.max(1));
+        let mut workers = Vec::with_capacity(num_workers);
+        for _ in 0..num_workers {
+            let queue = Arc::clone(&queue);
             let client = Arc::clone(&client);
-            tokio::spawn(async move {
-                let asset_metadata = client.assets_by_id(&asset).await;
-                match tx.send(asset_metadata).await {
-                    Err(e) => eprintln!("Failed to send asset metadata: {:?}", e),
-                    _ => (),
-                };
-            });
-        }
+            let download_service = Arc::clone(&download_service);
+            let download_semaphore = Arc::clone(&download_semaphore);
+            let limiter = limiter.clone();
+            let completed = Arc::clone(&completed);
+            let failed = Arc::clone(&failed);
+            let progress_tx = progress_tx.clone();
 
-        while let Some(result) = rx.recv().await {
-            remaining_tasks = remaining_tasks.saturating_sub(1);
-            let mut is_valid = false;
-            if let Ok(metadata) = result {
-                if let Some(onchain_metadata) = metadata.onchain_metadata {
-                    // checking if the downloaded data object is the image
-                    if let Some(Value::Array(files)) = onchain_metadata.get("files") {
-                        // check if the download source is available for the field
-                        if let Some(Value::String(src)) = files[0].get("src") {
-                            // check if the source is available
-                            let url = ipfs_to_http(&src);
-                            if let Ok(url) = url {
-                                asset_metadata.push(Asset {
-                                    asset: metadata.asset.clone(),
-                                    src: src.clone(),
-                                });
-                                is_valid = true;
-
-                                let mut extension = "png";
-                                if let Some(Value::String(media_type)) = files[0].get("mediaType") {
-                                    match media_type.as_str() {
-                                        "image/png" => {
-                                            extension = "png";
-                                        }
-                                        _ => (),
-                                    }
-                                }
-                                let asset = metadata.asset.clone();
-                                let filename = format!("{}.{}", asset, extension);
-
-                                // create a new task to download the image associated with the asset
-                                let permit = semaphore
-                                    .clone()
-                                    .acquire_owned()
-                                    .await
-                                    .expect("Failed to acquire semaphore"); // Acquire a permit from the semaphore
+            workers.push(tokio::spawn(async move {
+                let mut asset_metadata = Vec::new();
+                let mut download_tasks = Vec::new();
+
+                loop {
+                    let asset_id = queue.lock().await.next();
+                    let asset_id = match asset_id {
+                        Some(asset_id) => asset_id,
+                        None => break,
+                    };
 
-                                let download_service = Arc::clone(&download_service);
+                    if let Some(limiter) = &limiter {
+                        limiter.acquire().await;
+                    }
 
-                                println!("Downloading asset: {:?}", url);
-                                let url = Url::parse(&url)?;
-                                let download_task = tokio::spawn(async move {
-                                    let _permit = permit;
-                                    match download_service.download_and_save(url, filename).await {
-                                        Err(e) => eprintln!("Failed to download asset: {:?}", e),
-                                        _ => (),
-                                    }
-                                });
+                    match client.assets_by_id(&asset_id).await {
+                        Ok(metadata) => {
+                            let (found, tasks) = process_asset_metadata(
+                                metadata,
+                                variant,
+                                &download_service,
+                                &download_semaphore,
+                            )
+                            .await;
 
-                                download_tasks.push(download_task);
+                            if found.is_empty() {
+                                failed.fetch_add(1, Ordering::Relaxed);
+                            } else {
+                                completed.fetch_add(1, Ordering::Relaxed);
                             }
+                            asset_metadata.extend(found);
+                            download_tasks.extend(tasks);
+                        }
+                        Err(e) => {
+                            eprintln!("Failed to fetch asset metadata for {}: {:?}", asset_id, e);
+                            failed.fetch_add(1, Ordering::Relaxed);
                         }
                     }
-                }
-            }
 
-            if !is_valid {
-                if let Some(asset) = assets.pop() {
-                    let tx = tx.clone();
-                    remaining_tasks += 1;
-                    let client = self.client.clone();
-                    tokio::spawn(async move {
-                        let asset_metadata = client.assets_by_id(&asset).await;
-                        tx.send(asset_metadata).await.unwrap();
-                    });
+                    // Report progress via the channel only; the caller owns
+                    // how (or whether) it's displayed, rather than this
+                    // worker printing the same event itself.
+                    if let Some(tx) = &progress_tx {
+                        let _ = tx.send(Progress {
+                            completed: completed.load(Ordering::Relaxed),
+                            failed: failed.load(Ordering::Relaxed),
+                            total,
+                        });
+                    }
                 }
-            }
 
-            if remaining_tasks == 0 {
-                break;
+                (asset_metadata, download_tasks)
+            }));
+        }
+
+        let mut asset_metadata = Vec::new();
+        let mut download_tasks = Vec::new();
+        for worker in workers {
+            if let Ok((found, tasks)) = worker.await {
+                asset_metadata.extend(found);
+                download_tasks.extend(tasks);
             }
         }
 
@@ -164,3 +221,269 @@ impl BlockFrostService {
         Ok(asset_metadata)
     }
 }
+
+/// One `files` entry selected for download, with its target filename
+/// already disambiguated against every other selected entry for the same
+/// asset.
+struct PlannedDownload {
+    src: String,
+    media_type: String,
+    filename: String,
+    expected_sha256: Option<String>,
+}
+
+/// Selects the entries of a `files` array matching the requested artwork
+/// `variant` and assigns each a `<asset_id>[...].<ext>` filename. Pure and
+/// side-effect free, so the filename-disambiguation logic can be tested
+/// without a network call or a spawned task.
+///
+/// Filenames are disambiguated by kind only when more than one entry is
+/// selected, to keep the common single-file case's filename unchanged; when
+/// two entries share the same kind and extension (e.g. neither `name`
+/// mentions "thumb"), kind alone isn't enough, so those also get an index
+/// suffix.
+fn plan_downloads(files: &[Value], variant: ArtworkVariant, asset_id: &str) -> Vec<PlannedDownload> {
+    // gather every file entry whose source is reachable and whose
+    // cover/thumbnail kind was requested, instead of only files[0]
+    let mut matches = Vec::new();
+    for file in files {
+        let src = match file.get("src").and_then(Value::as_str) {
+            Some(src) => src,
+            None => continue,
+        };
+        if ipfs_to_http(src, IPFS_GATEWAY).is_err() {
+            continue;
+        }
+        let media_type = file.get("mediaType").and_then(Value::as_str).unwrap_or("image/png");
+        let kind = classify_kind(file.get("name").and_then(Value::as_str));
+        if !variant.matches(kind) {
+            continue;
+        }
+        // Some book.io collections publish an expected digest alongside the
+        // file entry; when present it lets the unverified download path
+        // catch corruption too, not just the CID-verified one.
+        let expected_sha256 = file.get("sha256").and_then(Value::as_str).map(str::to_string);
+        matches.push((src.to_string(), media_type.to_string(), kind, expected_sha256));
+    }
+
+    let disambiguate = matches.len() > 1;
+    let mut kind_ext_counts: HashMap<(&str, &str), usize> = HashMap::new();
+    for (_, media_type, kind, _) in &matches {
+        let extension = extension_for_media_type(media_type);
+        *kind_ext_counts.entry((*kind, extension)).or_insert(0) += 1;
+    }
+    let mut kind_ext_seen: HashMap<(&str, &str), usize> = HashMap::new();
+
+    matches
+        .into_iter()
+        .map(|(src, media_type, kind, expected_sha256)| {
+            let extension = extension_for_media_type(&media_type);
+            let filename = if !disambiguate {
+                format!("{}.{}", asset_id, extension)
+            } else if kind_ext_counts[&(kind, extension)] > 1 {
+                let index = kind_ext_seen.entry((kind, extension)).or_insert(0);
+                let filename = format!("{}_{}_{}.{}", asset_id, kind, index, extension);
+                *index += 1;
+                filename
+            } else {
+                format!("{}_{}.{}", asset_id, kind, extension)
+            };
+            PlannedDownload { src, media_type, filename, expected_sha256 }
+        })
+        .collect()
+}
+
+/// Inspects one asset's on-chain metadata, selects the `files` entries
+/// matching the requested artwork `variant`, and spawns a download task for
+/// each match (bounded by `download_semaphore`). Returns the `Asset` records
+/// found and the spawned download tasks; an empty `Asset` vector means this
+/// asset had no usable image.
+async fn process_asset_metadata(
+    metadata: AssetDetails,
+    variant: ArtworkVariant,
+    download_service: &Arc<DownloadService>,
+    download_semaphore: &Arc<Semaphore>,
+) -> (Vec<Asset>, Vec<tokio::task::JoinHandle<()>>) {
+    let mut assets = Vec::new();
+    let mut tasks = Vec::new();
+
+    if let Some(onchain_metadata) = metadata.onchain_metadata {
+        // checking if the downloaded data object is the image
+        if let Some(Value::Array(files)) = onchain_metadata.get("files") {
+            for planned in plan_downloads(files, variant, &metadata.asset) {
+                let PlannedDownload { src, media_type, filename, expected_sha256 } = planned;
+
+                assets.push(Asset { asset: metadata.asset.clone(), src: src.clone(), media_type });
+
+                let download_service = Arc::clone(download_service);
+                let download_semaphore = Arc::clone(download_semaphore);
+
+                // Prefer verified, CID-checked retrieval; fall back to a plain
+                // gateway fetch for sources whose CID we can't parse. The
+                // download permit is acquired inside the spawned task, not
+                // here, so a metadata worker can move on to the next asset
+                // immediately instead of blocking until a download slot
+                // frees up — metadata_workers and download_permits stay
+                // independent knobs.
+                let download_task = tokio::spawn(async move {
+                    let _permit = download_semaphore
+                        .acquire_owned()
+                        .await
+                        .expect("Failed to acquire semaphore");
+
+                    let outcome = if download_service.verify() {
+                        match ipfs_to_cid(&src) {
+                            Ok((cid, path)) => {
+                                println!("Downloading (verified) asset: {:?}", src);
+                                download_service.download_verified(cid, &src, filename, path).await
+                            }
+                            Err(e) => {
+                                eprintln!(
+                                    "Cannot verify {:?} ({}), falling back to unverified download",
+                                    src, e
+                                );
+                                download_unverified(&download_service, &src, filename, expected_sha256)
+                                    .await
+                            }
+                        }
+                    } else {
+                        download_unverified(&download_service, &src, filename, expected_sha256).await
+                    };
+
+                    if let Err(e) = outcome {
+                        eprintln!("Failed to download asset: {:?}", e);
+                    }
+                });
+
+                tasks.push(download_task);
+            }
+        }
+    }
+
+    (assets, tasks)
+}
+
+/// Falls back to a plain (unverified) gateway fetch, used when an asset's
+/// `src` doesn't carry a parseable CID or verification is turned off.
+/// `expected_sha256`, when the on-chain metadata published one, still lets
+/// `download_and_save` catch corruption even without CID verification.
+async fn download_unverified(
+    download_service: &DownloadService,
+    src: &str,
+    filename: String,
+    expected_sha256: Option<String>,
+) -> Result<()> {
+    println!("Downloading asset: {:?}", src);
+    download_service.download_and_save(src, filename, expected_sha256).await
+}
+
+/// Classifies a `files` entry as book.io's low-res `"thumbnail"` or its
+/// high-resolution `"cover"`, based on the entry's `name` field.
+fn classify_kind(name: Option<&str>) -> &'static str {
+    match name {
+        Some(name) if name.to_lowercase().contains("thumb") => "thumbnail",
+        _ => "cover",
+    }
+}
+
+/// Enforces a minimum gap between requests, shared across every metadata
+/// worker, so the aggregate request rate stays under a configured cap.
+struct RateLimiter {
+    min_interval: Duration,
+    next_allowed: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    fn new(requests_per_second: u32) -> Self {
+        let min_interval = Duration::from_secs_f64(1.0 / requests_per_second.max(1) as f64);
+        Self { min_interval, next_allowed: Mutex::new(Instant::now()) }
+    }
+
+    async fn acquire(&self) {
+        let mut next_allowed = self.next_allowed.lock().await;
+        let now = Instant::now();
+        if *next_allowed > now {
+            tokio::time::sleep(*next_allowed - now).await;
+        }
+        *next_allowed = next_allowed.max(now) + self.min_interval;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn classify_kind_detects_thumbnail_by_name() {
+        assert_eq!(classify_kind(Some("Book Thumbnail")), "thumbnail");
+        assert_eq!(classify_kind(Some("THUMB.png")), "thumbnail");
+    }
+
+    #[test]
+    fn classify_kind_defaults_to_cover() {
+        assert_eq!(classify_kind(Some("Front Cover")), "cover");
+        assert_eq!(classify_kind(None), "cover");
+    }
+
+    #[test]
+    fn artwork_variant_matches_selects_the_right_kind() {
+        assert!(ArtworkVariant::Cover.matches("cover"));
+        assert!(!ArtworkVariant::Cover.matches("thumbnail"));
+        assert!(ArtworkVariant::Thumbnail.matches("thumbnail"));
+        assert!(!ArtworkVariant::Thumbnail.matches("cover"));
+        assert!(ArtworkVariant::Both.matches("cover"));
+        assert!(ArtworkVariant::Both.matches("thumbnail"));
+    }
+
+    #[test]
+    fn plan_downloads_keeps_plain_filename_for_a_single_match() {
+        let files = vec![json!({"src": "ipfs://cid1", "mediaType": "image/png", "name": "cover"})];
+
+        let planned = plan_downloads(&files, ArtworkVariant::Cover, "asset1");
+
+        assert_eq!(planned.len(), 1);
+        assert_eq!(planned[0].filename, "asset1.png");
+    }
+
+    #[test]
+    fn plan_downloads_disambiguates_distinct_kinds_without_index() {
+        let files = vec![
+            json!({"src": "ipfs://cid1", "mediaType": "image/png", "name": "cover"}),
+            json!({"src": "ipfs://cid2", "mediaType": "image/png", "name": "thumb"}),
+        ];
+
+        let planned = plan_downloads(&files, ArtworkVariant::Both, "asset1");
+
+        let filenames: Vec<&str> = planned.iter().map(|p| p.filename.as_str()).collect();
+        assert_eq!(filenames, vec!["asset1_cover.png", "asset1_thumbnail.png"]);
+    }
+
+    #[test]
+    fn plan_downloads_disambiguates_same_kind_extension_collisions_by_index() {
+        let files = vec![
+            json!({"src": "ipfs://cid1", "mediaType": "image/png", "name": "cover-a"}),
+            json!({"src": "ipfs://cid2", "mediaType": "image/png", "name": "cover-b"}),
+        ];
+
+        let planned = plan_downloads(&files, ArtworkVariant::Both, "asset1");
+
+        assert_eq!(planned.len(), 2);
+        let filenames: Vec<&str> = planned.iter().map(|p| p.filename.as_str()).collect();
+        assert_ne!(filenames[0], filenames[1]);
+        assert_eq!(filenames, vec!["asset1_cover_0.png", "asset1_cover_1.png"]);
+    }
+
+    #[tokio::test]
+    async fn rate_limiter_enforces_minimum_interval_between_acquires() {
+        let limiter = RateLimiter::new(10); // ~100ms minimum gap
+        let start = Instant::now();
+
+        limiter.acquire().await;
+        limiter.acquire().await;
+        limiter.acquire().await;
+
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(180), "acquires were not spaced out: {:?}", elapsed);
+    }
+}
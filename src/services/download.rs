@@ -1,29 +1,158 @@
+use crate::utils::util::{ipfs_to_car_url, ipfs_to_http, IPFS_GATEWAY};
 use anyhow::{Context, Result};
 use bytes::Bytes;
-use reqwest::{Client, Url};
-use std::path::PathBuf;
+use cid::Cid;
+use rand::Rng;
+use reqwest::{Client, Response, StatusCode};
+use rs_car_ipfs::single_file::read_single_file_seek;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{fmt, path::PathBuf, time::Duration};
 use tempfile::NamedTempFile;
-use tokio::fs::File;
-use tokio::io::AsyncWriteExt;
+use tokio::fs::{File, OpenOptions};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio_stream::StreamExt;
+use tokio_util::compat::TokioAsyncReadCompatExt;
+use tokio_util::io::StreamReader;
+
+/// Sidecar metadata written alongside a downloaded file, recording enough
+/// to verify it later and to detect when two assets share identical content.
+#[derive(Debug, Serialize, Deserialize)]
+struct DownloadMeta {
+    sha256: String,
+    size: u64,
+    source: String,
+}
+
+/// Which IPFS gateways `DownloadService` tries, and how hard it retries a
+/// failing one before moving on to the next.
+#[derive(Debug, Clone)]
+pub struct GatewayConfig {
+    pub gateways: Vec<String>,
+    pub max_attempts: u32,
+    pub base_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Per-request timeout, covering connect and the full response body. A
+    /// gateway that accepts the connection and then stalls mid-response
+    /// trips this instead of hanging the fetch forever.
+    pub request_timeout: Duration,
+}
+
+impl Default for GatewayConfig {
+    fn default() -> Self {
+        Self {
+            // A handful of independently-operated public gateways, so one
+            // slow or failing gateway doesn't break the whole fetch.
+            gateways: vec![
+                IPFS_GATEWAY.to_string(),
+                "https://dweb.link/ipfs/".to_string(),
+                "https://cloudflare-ipfs.com/ipfs/".to_string(),
+            ],
+            max_attempts: 3,
+            base_backoff: Duration::from_millis(200),
+            max_backoff: Duration::from_millis(800),
+            request_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// One failed attempt against one gateway, recorded so a [`GatewayFetchError`]
+/// can report exactly what was tried.
+#[derive(Debug)]
+pub struct GatewayAttemptFailure {
+    pub gateway: String,
+    pub attempt: u32,
+    pub error: String,
+}
+
+/// Returned when every configured gateway has exhausted its retry budget
+/// without a successful response.
+#[derive(Debug)]
+pub struct GatewayFetchError {
+    pub failures: Vec<GatewayAttemptFailure>,
+}
+
+impl fmt::Display for GatewayFetchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "all {} gateway attempt(s) failed:", self.failures.len())?;
+        for failure in &self.failures {
+            writeln!(f, "  - {} (attempt {}): {}", failure.gateway, failure.attempt, failure.error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for GatewayFetchError {}
 
 /// DownloadService: it has one member: output_dir.
 pub struct DownloadService {
     output_dir: PathBuf,
     client: Client,
+    verify: bool,
+    gateway_config: GatewayConfig,
 }
 
 impl DownloadService {
     /// Constructor for DownloadService. Takes a path where files will be saved.
     pub fn new(output_dir: impl Into<PathBuf>) -> Self {
-        Self { output_dir: output_dir.into(), client: Client::new() }
+        Self {
+            output_dir: output_dir.into(),
+            client: Client::new(),
+            verify: false,
+            gateway_config: GatewayConfig::default(),
+        }
+    }
+
+    /// Enables (or disables) CID-verified retrieval for IPFS downloads.
+    ///
+    /// When enabled, callers should fetch assets through
+    /// [`DownloadService::download_verified`] instead of
+    /// [`DownloadService::download_and_save`].
+    pub fn with_verify(mut self, verify: bool) -> Self {
+        self.verify = verify;
+        self
+    }
+
+    /// Configures the gateway list and retry/backoff budget used to fetch
+    /// IPFS content.
+    pub fn with_gateway_config(mut self, gateway_config: GatewayConfig) -> Self {
+        self.gateway_config = gateway_config;
+        self
+    }
+
+    /// Whether this service is configured for CID-verified retrieval.
+    pub fn verify(&self) -> bool {
+        self.verify
     }
 
     /// The method responsible for downloading and saving a file.
     ///
-    /// 'url' is the web location of the file to fetch, 'filename' is the name
-    /// for the file once it's saved locally.
-    pub async fn download_and_save(&self, url: Url, filename: String) -> Result<()> {
+    /// `ipfs_src` is an `ipfs://<cid>` URI, tried against each configured
+    /// gateway in turn (with exponential backoff between retries on the
+    /// same gateway) until one responds successfully. `filename` is the
+    /// name for the file once it's saved locally. A SHA-256 digest is
+    /// computed incrementally as the response streams in; if
+    /// `expected_sha256` is given and doesn't match, the partial download is
+    /// discarded and an error is returned instead of silently persisting bad
+    /// data. Otherwise the digest, byte length and source URL are recorded
+    /// in a `<filename>.meta.json` sidecar, and if a file with the same
+    /// digest is already on disk the downloaded copy is discarded and
+    /// `filename` is hard-linked to that file (or copied from it where
+    /// linking fails).
+    ///
+    /// The download is resumable: progress is kept in a `<filename>.part`
+    /// file in the output directory, keyed to `filename`. If that file
+    /// already holds bytes from a previous, interrupted attempt (e.g. a
+    /// Ctrl+C abort), this re-requests the remainder with a `Range` header
+    /// and appends to it; when a gateway replies `200 OK` instead of
+    /// `206 Partial Content`, the partial file is truncated and the full
+    /// response is written from zero.
+    pub async fn download_and_save(
+        &self,
+        ipfs_src: &str,
+        filename: String,
+        expected_sha256: Option<String>,
+    ) -> Result<()> {
         // Clone the path to the output directory and push filename onto it
         let mut output_path = self.output_dir.clone();
         output_path.push(&filename);
@@ -38,21 +167,41 @@ impl DownloadService {
         // Only proceed with fetch and write operations if the file doesn't exist in
         // the output directory yet.
         if !output_path.exists() {
-            // Create temporary file in the output directory
-            let temp_file =
-                NamedTempFile::new_in(&self.output_dir).context("Failed to create temp file")?;
+            // The partial download lives at a path keyed to the target filename
+            // (rather than a randomly-named tempfile) so a later call for the
+            // same asset can find and resume it.
+            let temp_file_path = self.output_dir.join(format!("{}.part", filename));
+            let written = tokio::fs::metadata(&temp_file_path).await.map(|m| m.len()).unwrap_or(0);
 
-            // Record temporary file's path for later operations
-            let temp_file_path = temp_file.path().to_path_buf(); // keep tempfile path for later
+            // Fetch the file content, trying each gateway with backoff until one succeeds
+            let response = self.fetch_with_retry(ipfs_src, false, written).await?;
+            let source = response.url().to_string();
+            let resuming = written > 0 && response.status() == StatusCode::PARTIAL_CONTENT;
 
-            // Fetch the file content into the 'source' variable
-            let response = self.client.get(url).send().await.context("Failed downloading file")?;
+            // Seed the hasher and running size with whatever was already
+            // written on a previous attempt, or start clean if the gateway
+            // didn't honor our Range request.
+            let mut hasher = Sha256::new();
+            let mut size: u64 = 0;
+            if resuming {
+                let mut existing =
+                    File::open(&temp_file_path).await.context("Failed to open partial download")?;
+                let mut buf = Vec::with_capacity(written as usize);
+                existing.read_to_end(&mut buf).await.context("Failed to read partial download")?;
+                hasher.update(&buf);
+                size = buf.len() as u64;
+            }
 
-            let (tx, mut rx) = tokio::sync::mpsc::channel::<Result<Bytes, reqwest::Error>>(1024);
+            let mut dest = OpenOptions::new()
+                .create(true)
+                .write(true)
+                .append(resuming)
+                .truncate(!resuming)
+                .open(&temp_file_path)
+                .await
+                .context("Failed creating destination file")?;
 
-            // Initialize an async file instance pointing at the temp file
-            let mut dest =
-                File::create(&temp_file_path).await.context("Failed creating destination file")?;
+            let (tx, mut rx) = tokio::sync::mpsc::channel::<Result<Bytes, reqwest::Error>>(1024);
 
             // Spawn a task to read from the network
             let network_reader = tokio::spawn(async move {
@@ -62,20 +211,127 @@ impl DownloadService {
                 }
                 Result::<(), anyhow::Error>::Ok(())
             });
-            // Stream download
-            // While there are data chunks available in the source...
+
+            // Stream download, hashing each chunk as it arrives so the
+            // digest is known as soon as the last byte is written.
             while let Some(chunk) = rx.recv().await {
                 let chunk = chunk.context("Failed reading chunk from the stream")?;
+                hasher.update(&chunk);
+                size += chunk.len() as u64;
                 dest.write_all(&chunk).await.context("Failed to write chunk to output")?;
             }
 
             network_reader.await.context("Network read task failed")??;
+            dest.flush().await.context("Failed to flush destination file")?;
+
+            let digest = format!("{:x}", hasher.finalize());
+
+            if let Some(expected) = &expected_sha256 {
+                if !expected.eq_ignore_ascii_case(&digest) {
+                    tokio::fs::remove_file(&temp_file_path)
+                        .await
+                        .context("Failed to remove corrupted temp file")?;
+                    anyhow::bail!(
+                        "downloaded content for {} does not match expected sha256 (expected {}, got {})",
+                        filename,
+                        expected,
+                        digest
+                    );
+                }
+            }
 
-            // let bytes = response.bytes().await.context("failed converting respone to bytes")?;
-            // dest.write_all(&bytes).await.context("Failed to write to the file")?;
+            // Content-addressed dedup: if a file with this digest already
+            // exists, skip persisting a duplicate copy of the bytes.
+            match self.find_by_digest(&digest).await? {
+                Some(existing) => {
+                    tokio::fs::remove_file(&temp_file_path)
+                        .await
+                        .context("Failed to remove duplicate temp file")?;
+
+                    // The bytes are already on disk under `existing`; link (or,
+                    // failing that, copy) them under the new filename instead of
+                    // just skipping the write, so `output_path` always ends up
+                    // with the file it was asked to produce.
+                    let existing_path = self.output_dir.join(&existing);
+                    if tokio::fs::hard_link(&existing_path, &output_path).await.is_err() {
+                        tokio::fs::copy(&existing_path, &output_path)
+                            .await
+                            .context("Failed to materialize deduplicated content under new filename")?;
+                    }
+                    println!(
+                        "Deduplicated {}, content already stored as {}",
+                        filename, existing
+                    );
+                }
+                None => {
+                    // Persisting temp file (rename)
+                    // Once the temp file is fully written, rename it to the desired filename.
+                    tokio::fs::rename(&temp_file_path, &output_path)
+                        .await
+                        .context("Failed to persist temp file")?;
+                }
+            }
+
+            self.write_meta(&filename, &digest, size, &source).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Trustless counterpart to [`DownloadService::download_and_save`].
+    ///
+    /// `ipfs_src` is resolved against each configured gateway (with the same
+    /// retry/backoff budget) as a CAR (Content-Addressable aRchive) stream.
+    /// Every block's multihash is recomputed and checked against its CID,
+    /// the CAR's root CID is confirmed to equal `cid`, and the UnixFS DAG is
+    /// then walked to reassemble the file: a single-block file yields its
+    /// bytes directly, while a chunked file's dag-pb root node has its
+    /// linked child blocks concatenated in order. Only the fully verified
+    /// bytes are persisted, via the same tempfile-then-rename path used by
+    /// `download_and_save`.
+    ///
+    /// `path` is the UnixFS path of the target file inside `cid`'s DAG, as
+    /// split off by [`crate::utils::util::ipfs_to_cid`] (`None` when `cid`
+    /// already identifies the file directly).
+    pub async fn download_verified(
+        &self,
+        cid: Cid,
+        ipfs_src: &str,
+        filename: String,
+        path: Option<String>,
+    ) -> Result<()> {
+        let mut output_path = self.output_dir.clone();
+        output_path.push(&filename);
+
+        if !self.output_dir.exists() {
+            tokio::fs::create_dir_all(&self.output_dir)
+                .await
+                .context("Failed to create the output directory")?;
+        }
+
+        if !output_path.exists() {
+            // Verified downloads always fetch the full CAR; the UnixFS DAG can't
+            // be partially verified, so there's no range to resume from here.
+            let response = self.fetch_with_retry(ipfs_src, true, 0).await?;
+
+            let stream = StreamReader::new(
+                response.bytes_stream().map(|chunk| chunk.map_err(std::io::Error::other)),
+            )
+            .compat();
+
+            let bytes = read_single_file_seek(stream, &cid, path.as_deref())
+                .await
+                .context("Failed to verify CAR against requested CID")?;
+
+            let temp_file =
+                NamedTempFile::new_in(&self.output_dir).context("Failed to create temp file")?;
+            let temp_file_path = temp_file.path().to_path_buf();
+
+            let mut dest = File::create(&temp_file_path)
+                .await
+                .context("Failed creating destination file")?;
+            dest.write_all(&bytes).await.context("Failed to write verified content to output")?;
 
-            // Persisting temp file (rename)
-            // Once the temp file is fully written, rename it to the desired filename.
             tokio::fs::rename(temp_file_path, &output_path)
                 .await
                 .context("Failed to persist temp file")?;
@@ -83,4 +339,330 @@ impl DownloadService {
 
         Ok(())
     }
+
+    /// Resolves `ipfs_src` against each configured gateway in turn, retrying
+    /// a gateway with exponential backoff (capped, with jitter) before
+    /// moving on to the next one. Returns the first successful response, or
+    /// a [`GatewayFetchError`] listing every gateway/attempt that failed.
+    ///
+    /// When `range_from` is non-zero, a `Range: bytes=<range_from>-` header
+    /// is sent so a gateway supporting resumable transfers can reply
+    /// `206 Partial Content` with just the remainder of the content.
+    async fn fetch_with_retry(&self, ipfs_src: &str, car: bool, range_from: u64) -> Result<Response> {
+        let mut failures = Vec::new();
+
+        for gateway in &self.gateway_config.gateways {
+            let url = if car {
+                ipfs_to_car_url(ipfs_src, gateway)
+            } else {
+                ipfs_to_http(ipfs_src, gateway)
+            }
+            .map_err(|e| anyhow::anyhow!(e))?;
+
+            for attempt in 1..=self.gateway_config.max_attempts {
+                let mut request = self.client.get(&url).timeout(self.gateway_config.request_timeout);
+                if car {
+                    request = request.header("Accept", "application/vnd.ipld.car");
+                }
+                if range_from > 0 {
+                    request = request.header("Range", format!("bytes={}-", range_from));
+                }
+
+                match request.send().await {
+                    Ok(response) if response.status().is_success() => return Ok(response),
+                    Ok(response) => failures.push(GatewayAttemptFailure {
+                        gateway: gateway.clone(),
+                        attempt,
+                        error: format!("HTTP {}", response.status()),
+                    }),
+                    Err(e) => failures.push(GatewayAttemptFailure {
+                        gateway: gateway.clone(),
+                        attempt,
+                        error: e.to_string(),
+                    }),
+                }
+
+                if attempt < self.gateway_config.max_attempts {
+                    sleep_with_backoff(attempt, &self.gateway_config).await;
+                }
+            }
+        }
+
+        Err(GatewayFetchError { failures }.into())
+    }
+
+    /// Looks for an existing `<name>.meta.json` sidecar in the output
+    /// directory recording the given digest, returning the data filename it
+    /// describes if found.
+    async fn find_by_digest(&self, digest: &str) -> Result<Option<String>> {
+        let mut entries =
+            tokio::fs::read_dir(&self.output_dir).await.context("Failed to read output directory")?;
+
+        while let Some(entry) =
+            entries.next_entry().await.context("Failed to read directory entry")?
+        {
+            let path = entry.path();
+            let name = match path.file_name().and_then(|n| n.to_str()) {
+                Some(name) => name,
+                None => continue,
+            };
+            let data_name = match name.strip_suffix(".meta.json") {
+                Some(data_name) => data_name,
+                None => continue,
+            };
+
+            if let Ok(contents) = tokio::fs::read(&path).await {
+                if let Ok(meta) = serde_json::from_slice::<DownloadMeta>(&contents) {
+                    if meta.sha256 == digest {
+                        return Ok(Some(data_name.to_string()));
+                    }
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Writes the `<filename>.meta.json` sidecar recording a downloaded
+    /// file's digest, size and source URL.
+    async fn write_meta(&self, filename: &str, digest: &str, size: u64, source: &str) -> Result<()> {
+        let meta = DownloadMeta { sha256: digest.to_string(), size, source: source.to_string() };
+        let meta_path = self.output_dir.join(format!("{}.meta.json", filename));
+        let contents =
+            serde_json::to_vec_pretty(&meta).context("Failed to serialize download metadata")?;
+        tokio::fs::write(meta_path, contents)
+            .await
+            .context("Failed to write download metadata sidecar")?;
+        Ok(())
+    }
+}
+
+/// Sleeps for an exponential backoff (doubling from `base_backoff`, capped
+/// at `max_backoff`) plus a small jitter, before the next retry attempt.
+async fn sleep_with_backoff(attempt: u32, config: &GatewayConfig) {
+    let exponent = attempt.saturating_sub(1).min(16);
+    let backoff = config.base_backoff.saturating_mul(1u32 << exponent).min(config.max_backoff);
+    let jitter = Duration::from_millis(rand::thread_rng().gen_range(0..=50));
+    tokio::time::sleep(backoff + jitter).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito;
+
+    fn gateway_config_for(server_url: &str) -> GatewayConfig {
+        GatewayConfig { gateways: vec![format!("{}/ipfs/", server_url)], ..GatewayConfig::default() }
+    }
+
+    /// Builds a minimal single-block CARv1 fixture: a raw-codec leaf whose
+    /// CID's multihash is the real sha2-256 digest of `data`, wrapped in a
+    /// CAR header naming it as the sole root. Good enough for
+    /// `read_single_file_seek` to walk and verify against.
+    fn build_car_fixture(data: &[u8]) -> (Cid, Vec<u8>) {
+        let digest = Sha256::digest(data);
+        let multihash = multihash::Multihash::<64>::wrap(0x12, &digest).expect("digest fits");
+        let cid = Cid::new_v1(0x55, multihash);
+
+        // CBOR-encode {"version": 1, "roots": [42(h'00' + cid bytes)]}, the
+        // minimal CARv1 header shape.
+        let mut root_bytes = vec![0x00];
+        root_bytes.extend(cid.to_bytes());
+        let mut header = vec![0xA2];
+        header.extend(b"\x67version");
+        header.push(0x01);
+        header.extend(b"\x65roots");
+        header.push(0x81);
+        header.push(0xD8);
+        header.push(0x2A);
+        header.push(0x58);
+        header.push(root_bytes.len() as u8);
+        header.extend(&root_bytes);
+
+        let mut car = Vec::new();
+        write_uvarint(&mut car, header.len() as u64);
+        car.extend(&header);
+
+        let mut block = cid.to_bytes();
+        block.extend(data);
+        write_uvarint(&mut car, block.len() as u64);
+        car.extend(&block);
+
+        (cid, car)
+    }
+
+    fn write_uvarint(buf: &mut Vec<u8>, mut value: u64) {
+        loop {
+            let mut byte = (value & 0x7f) as u8;
+            value >>= 7;
+            if value != 0 {
+                byte |= 0x80;
+            }
+            buf.push(byte);
+            if value == 0 {
+                break;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn download_verified_accepts_a_correctly_verified_car() {
+        let (cid, car) = build_car_fixture(b"verified-bytes");
+
+        let mut server = mockito::Server::new();
+        let mock = server
+            .mock("GET", "/ipfs/testcid?format=car")
+            .match_header("accept", "application/vnd.ipld.car")
+            .with_status(200)
+            .with_body(car)
+            .create();
+
+        let output_dir = tempfile::tempdir().unwrap();
+        let service = DownloadService::new(output_dir.path())
+            .with_verify(true)
+            .with_gateway_config(gateway_config_for(&server.url()));
+
+        service
+            .download_verified(cid, "ipfs://testcid", "verified.bin".to_string(), None)
+            .await
+            .unwrap();
+
+        let contents = tokio::fs::read(output_dir.path().join("verified.bin")).await.unwrap();
+        assert_eq!(contents, b"verified-bytes");
+
+        mock.assert();
+    }
+
+    #[tokio::test]
+    async fn download_verified_rejects_a_car_with_tampered_block_data() {
+        let (cid, mut car) = build_car_fixture(b"verified-bytes");
+        let last = car.len() - 1;
+        car[last] ^= 0xFF; // corrupt a data byte without touching the header/CID
+
+        let mut server = mockito::Server::new();
+        let mock = server
+            .mock("GET", "/ipfs/testcid?format=car")
+            .match_header("accept", "application/vnd.ipld.car")
+            .with_status(200)
+            .with_body(car)
+            .create();
+
+        let output_dir = tempfile::tempdir().unwrap();
+        let service = DownloadService::new(output_dir.path())
+            .with_verify(true)
+            .with_gateway_config(gateway_config_for(&server.url()));
+
+        let result = service
+            .download_verified(cid, "ipfs://testcid", "verified.bin".to_string(), None)
+            .await;
+
+        assert!(result.is_err());
+        assert!(!output_dir.path().join("verified.bin").exists());
+
+        mock.assert();
+    }
+
+    #[tokio::test]
+    async fn download_and_save_falls_back_to_the_next_gateway() {
+        let mut failing_gateway = mockito::Server::new();
+        let failing_mock = failing_gateway
+            .mock("GET", "/ipfs/testcid")
+            .with_status(502)
+            .expect(1)
+            .create();
+
+        let mut working_gateway = mockito::Server::new();
+        let working_mock = working_gateway
+            .mock("GET", "/ipfs/testcid")
+            .with_status(200)
+            .with_body("from-the-second-gateway")
+            .create();
+
+        let output_dir = tempfile::tempdir().unwrap();
+        let gateway_config = GatewayConfig {
+            gateways: vec![
+                format!("{}/ipfs/", failing_gateway.url()),
+                format!("{}/ipfs/", working_gateway.url()),
+            ],
+            max_attempts: 1,
+            ..GatewayConfig::default()
+        };
+        let service = DownloadService::new(output_dir.path()).with_gateway_config(gateway_config);
+
+        service.download_and_save("ipfs://testcid", "fallback.png".to_string(), None).await.unwrap();
+
+        let contents = tokio::fs::read(output_dir.path().join("fallback.png")).await.unwrap();
+        assert_eq!(contents, b"from-the-second-gateway");
+
+        failing_mock.assert();
+        working_mock.assert();
+    }
+
+    #[tokio::test]
+    async fn download_and_save_dedups_identical_content_across_filenames() {
+        let mut server = mockito::Server::new();
+        let mock = server
+            .mock("GET", "/ipfs/testcid")
+            .with_status(200)
+            .with_body("dedup-body")
+            .expect(2)
+            .create();
+
+        let output_dir = tempfile::tempdir().unwrap();
+        let service = DownloadService::new(output_dir.path())
+            .with_gateway_config(gateway_config_for(&server.url()));
+
+        service.download_and_save("ipfs://testcid", "a.png".to_string(), None).await.unwrap();
+        service.download_and_save("ipfs://testcid", "b.png".to_string(), None).await.unwrap();
+
+        let a = tokio::fs::read(output_dir.path().join("a.png")).await.unwrap();
+        let b = tokio::fs::read(output_dir.path().join("b.png")).await.unwrap();
+        assert_eq!(a, b"dedup-body");
+        assert_eq!(b, b"dedup-body");
+
+        mock.assert();
+    }
+
+    #[tokio::test]
+    async fn download_and_save_resumes_partial_download_via_range() {
+        let mut server = mockito::Server::new();
+        let mock = server
+            .mock("GET", "/ipfs/testcid")
+            .match_header("range", "bytes=5-")
+            .with_status(206)
+            .with_body("world")
+            .create();
+
+        let output_dir = tempfile::tempdir().unwrap();
+        tokio::fs::write(output_dir.path().join("resumed.png.part"), b"hello").await.unwrap();
+
+        let service = DownloadService::new(output_dir.path())
+            .with_gateway_config(gateway_config_for(&server.url()));
+
+        service.download_and_save("ipfs://testcid", "resumed.png".to_string(), None).await.unwrap();
+
+        let contents = tokio::fs::read(output_dir.path().join("resumed.png")).await.unwrap();
+        assert_eq!(contents, b"helloworld");
+
+        mock.assert();
+    }
+
+    #[tokio::test]
+    async fn sleep_with_backoff_caps_at_max_backoff() {
+        let config = GatewayConfig {
+            gateways: vec![],
+            max_attempts: 3,
+            base_backoff: Duration::from_millis(200),
+            max_backoff: Duration::from_millis(250),
+            ..GatewayConfig::default()
+        };
+
+        let start = tokio::time::Instant::now();
+        sleep_with_backoff(10, &config).await;
+        let elapsed = start.elapsed();
+
+        // exponent at attempt 10 would blow way past max_backoff uncapped;
+        // confirm it's clamped (plus a little slack for the jitter).
+        assert!(elapsed <= Duration::from_millis(400), "backoff not capped: {:?}", elapsed);
+    }
 }
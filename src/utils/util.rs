@@ -1,10 +1,103 @@
+use cid::Cid;
+
 pub const IPFS_GATEWAY: &str = "https://ipfs.io/ipfs/";
 
-pub fn ipfs_to_http(ipfs_hash: &str) -> Result<String, &'static str> {
-    if ipfs_hash.starts_with("ipfs://") {
-        let hash = &ipfs_hash[7..];
-        Ok(format!("{}{}", IPFS_GATEWAY, hash))
+/// Rewrites an `ipfs://<cid>` URI into an HTTP URL served by `gateway`.
+///
+/// `gateway` is expected to include a trailing slash, e.g. `"https://ipfs.io/ipfs/"`.
+pub fn ipfs_to_http(ipfs_hash: &str, gateway: &str) -> Result<String, &'static str> {
+    if let Some(hash) = ipfs_hash.strip_prefix("ipfs://") {
+        Ok(format!("{}{}", gateway, hash))
     } else {
         Err("Invalid IPFS hash, must start with 'ipfs://' prefix")
     }
 }
+
+/// Parses the CID embedded in an `ipfs://<cid>[/<path>]` URI, splitting off
+/// any trailing UnixFS path (book.io `src` entries are routinely
+/// `ipfs://<dirCID>/<file>.png`, pointing at a file inside a directory DAG
+/// rather than at a file CID directly).
+///
+/// Returns the root CID that a trustless gateway response must be verified
+/// against, plus the path (if any) identifying which file inside that DAG
+/// to extract.
+pub fn ipfs_to_cid(ipfs_hash: &str) -> Result<(Cid, Option<String>), &'static str> {
+    let hash =
+        ipfs_hash.strip_prefix("ipfs://").ok_or("Invalid IPFS hash, must start with 'ipfs://' prefix")?;
+    let (cid_part, path) = match hash.split_once('/') {
+        Some((cid_part, path)) => (cid_part, Some(path.to_string())),
+        None => (hash, None),
+    };
+    let cid = Cid::try_from(cid_part).map_err(|_| "Invalid IPFS hash, not a parseable CID")?;
+    Ok((cid, path))
+}
+
+/// Builds the URL for a trustless CAR (Content-Addressable aRchive) export
+/// of the content behind an `ipfs://<cid>` URI, using the `?format=car`
+/// convention supported by trustless gateways.
+pub fn ipfs_to_car_url(ipfs_hash: &str, gateway: &str) -> Result<String, &'static str> {
+    ipfs_to_http(ipfs_hash, gateway).map(|url| format!("{}?format=car", url))
+}
+
+/// Maps a `mediaType` value from asset metadata to the file extension used
+/// when persisting the downloaded artwork. Falls back to `"bin"` for
+/// unrecognized media types rather than guessing.
+pub fn extension_for_media_type(media_type: &str) -> &'static str {
+    match media_type {
+        "image/png" => "png",
+        "image/jpeg" => "jpg",
+        "image/gif" => "gif",
+        "image/webp" => "webp",
+        "image/svg+xml" => "svg",
+        _ => "bin",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_CID: &str = "bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi";
+
+    #[test]
+    fn ipfs_to_cid_parses_bare_cid() {
+        let hash = format!("ipfs://{}", SAMPLE_CID);
+        let (cid, path) = ipfs_to_cid(&hash).unwrap();
+        assert_eq!(cid.to_string(), SAMPLE_CID);
+        assert_eq!(path, None);
+    }
+
+    #[test]
+    fn ipfs_to_cid_splits_trailing_unixfs_path() {
+        let hash = format!("ipfs://{}/cover.png", SAMPLE_CID);
+        let (cid, path) = ipfs_to_cid(&hash).unwrap();
+        assert_eq!(cid.to_string(), SAMPLE_CID);
+        assert_eq!(path.as_deref(), Some("cover.png"));
+    }
+
+    #[test]
+    fn ipfs_to_cid_rejects_missing_prefix() {
+        assert!(ipfs_to_cid(SAMPLE_CID).is_err());
+    }
+
+    #[test]
+    fn ipfs_to_car_url_appends_format_param() {
+        let hash = format!("ipfs://{}/cover.png", SAMPLE_CID);
+        let url = ipfs_to_car_url(&hash, IPFS_GATEWAY).unwrap();
+        assert_eq!(url, format!("{}{}/cover.png?format=car", IPFS_GATEWAY, SAMPLE_CID));
+    }
+
+    #[test]
+    fn extension_for_media_type_maps_known_types() {
+        assert_eq!(extension_for_media_type("image/png"), "png");
+        assert_eq!(extension_for_media_type("image/jpeg"), "jpg");
+        assert_eq!(extension_for_media_type("image/gif"), "gif");
+        assert_eq!(extension_for_media_type("image/webp"), "webp");
+        assert_eq!(extension_for_media_type("image/svg+xml"), "svg");
+    }
+
+    #[test]
+    fn extension_for_media_type_falls_back_to_bin() {
+        assert_eq!(extension_for_media_type("application/octet-stream"), "bin");
+    }
+}